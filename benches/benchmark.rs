@@ -5,17 +5,17 @@ use once_cell::sync::Lazy;
 use criterion::Criterion;
 use criterion::{criterion_group, criterion_main};
 
-const LONG_TEXT: Lazy<Vec<&str>> = Lazy::new(|| {
+static LONG_TEXT: Lazy<Vec<&str>> = Lazy::new(|| {
     const TEXT: &str = include_str!("../assets/long_text.txt");
     TEXT.split(" ").collect()
 });
 
-const MIDDLE_TEXT: Lazy<Vec<&str>> = Lazy::new(|| {
+static MIDDLE_TEXT: Lazy<Vec<&str>> = Lazy::new(|| {
     const TEXT: &str = include_str!("../assets/middle_text.txt");
     TEXT.split(" ").collect()
 });
 
-const SHORT_TEXT: Lazy<Vec<&str>> = Lazy::new(|| {
+static SHORT_TEXT: Lazy<Vec<&str>> = Lazy::new(|| {
     const TEXT: &str = include_str!("../assets/short_text.txt");
     TEXT.split(" ").collect()
 });
@@ -49,9 +49,12 @@ fn bench_create_cdf_model(c: &mut Criterion) {
 }
 
 fn bench_generate_element_using_wam(c: &mut Criterion) {
-    let mut model_1 = MarkovChain::from(&SHORT_TEXT);
-    let mut model_2 = MarkovChain::from(&MIDDLE_TEXT);
-    let mut model_3 = MarkovChain::from(&LONG_TEXT);
+    let model_1 = MarkovChain::from(&SHORT_TEXT);
+    let model_2 = MarkovChain::from(&MIDDLE_TEXT);
+    let model_3 = MarkovChain::from(&LONG_TEXT);
+    let mut walker_1 = model_1.spawn_walker();
+    let mut walker_2 = model_2.spawn_walker();
+    let mut walker_3 = model_3.spawn_walker();
 
     let mut result = [""; 10];
 
@@ -60,7 +63,7 @@ fn bench_generate_element_using_wam(c: &mut Criterion) {
     c.bench_function("generate_element_from_short_text_using_wam", |b| {
         b.iter(|| {
             for r in &mut result {
-                *r = model_1.next_rng(&mut rng);
+                *r = walker_1.next_rng(&mut rng);
             }
         })
     });
@@ -68,7 +71,7 @@ fn bench_generate_element_using_wam(c: &mut Criterion) {
     c.bench_function("generate_element_from_middle_text_using_wam", |b| {
         b.iter(|| {
             for r in &mut result {
-                *r = model_2.next_rng(&mut rng);
+                *r = walker_2.next_rng(&mut rng);
             }
         })
     });
@@ -76,7 +79,7 @@ fn bench_generate_element_using_wam(c: &mut Criterion) {
     c.bench_function("generate_element_from_long_text_using_wam", |b| {
         b.iter(|| {
             for r in &mut result {
-                *r = model_3.next_rng(&mut rng);
+                *r = walker_3.next_rng(&mut rng);
             }
         })
     });
@@ -116,12 +119,83 @@ fn bench_generate_element_using_cdf(c: &mut Criterion) {
     });
 }
 
+fn bench_round_trip_cdf_model(c: &mut Criterion) {
+    let text: Vec<String> = LONG_TEXT.iter().map(|s| s.to_string()).collect();
+    let model = MarkovCSM::from(&text);
+
+    let json = serde_json::to_string(&model).unwrap();
+    let restored: MarkovCSM<String> = serde_json::from_str(&json).unwrap();
+    assert_eq!(restored, model, "cm_dist must survive a JSON round trip.");
+
+    c.bench_function("round_trip_cdf_model_from_long_text", |b| {
+        b.iter(|| {
+            let json = serde_json::to_string(&model).unwrap();
+            let restored: MarkovCSM<String> = serde_json::from_str(&json).unwrap();
+            restored == model
+        })
+    });
+}
+
+fn bench_convert_cdf_model(c: &mut Criterion) {
+    let model = MarkovCSM::from(&LONG_TEXT);
+    let converted: MarkovChain<&str> = MarkovCSM::from(&LONG_TEXT).into();
+    for (i, from) in model.elements.iter().enumerate() {
+        let mut prev_p = 0.0;
+        for (j, to) in model.elements.iter().enumerate() {
+            let p = model.cm_dist[i][j];
+            let expected = if p > prev_p { (p - prev_p) as f64 } else { 0.0 };
+            let actual = converted.transition_probability(from, to).unwrap();
+            assert!(
+                (actual - expected).abs() < 1e-5,
+                "The converted model must have the same distribution."
+            );
+            prev_p = p;
+        }
+    }
+
+    c.bench_function("convert_cdf_model_from_long_text", |b| {
+        b.iter(|| -> MarkovChain<&str> { MarkovCSM::from(&LONG_TEXT).into() })
+    });
+}
+
+fn bench_generate_element_with_temperature_using_cdf(c: &mut Criterion) {
+    // "a" is followed by "b", "c" and "d" with 1/3 each, and never by "z".
+    let mut cold = MarkovCSM::from(&["a", "b", "a", "c", "a", "d", "z"]);
+    let mut rng = rand::thread_rng();
+    for _ in 0..100 {
+        cold.pre_index = 0;
+        let next = *cold.next_with_temperature(&mut rng, 1e-3);
+        assert!(
+            ["b", "c", "d"].contains(&next),
+            "A low temperature must not pick a state with probability 0."
+        );
+    }
+
+    let mut model = MarkovCSM::from(&LONG_TEXT);
+
+    let mut result = [""; 10];
+
+    c.bench_function(
+        "generate_element_with_temperature_from_long_text_using_cdf",
+        |b| {
+            b.iter(|| {
+                for r in &mut result {
+                    *r = model.next_with_temperature(&mut rng, 0.5);
+                }
+            })
+        },
+    );
+}
+
 criterion_group!(
     benches,
     bench_create_wam_model,
     bench_create_cdf_model,
     bench_generate_element_using_wam,
     bench_generate_element_using_cdf,
+    bench_generate_element_with_temperature_using_cdf,
+    bench_round_trip_cdf_model,
+    bench_convert_cdf_model,
 );
 criterion_main!(benches);
 
@@ -130,8 +204,9 @@ criterion_main!(benches);
 // Markov chain with Cumulative Distribution Function
 
 use rand::prelude::*;
+use serde::{Deserialize, Serialize};
 
-#[derive(Debug, PartialEq)]
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
 pub struct MarkovCSM<T> {
     elements: Vec<T>,
     cm_dist: Vec<Vec<f32>>,
@@ -148,9 +223,9 @@ where
 {
     fn new(elements: Vec<T>, cm_dist: Vec<Vec<f32>>, pre_index: usize) -> MarkovCSM<T> {
         MarkovCSM {
-            elements: elements,
-            cm_dist: cm_dist,
-            pre_index: pre_index,
+            elements,
+            cm_dist,
+            pre_index,
         }
     }
 
@@ -161,7 +236,7 @@ where
 
         let elements_len = non_dup_elements.len();
 
-        let mut state_freq = vec![vec![0; elements_len]; elements_len];
+        let mut state_freq = vec![vec![0u64; elements_len]; elements_len];
         let mut pre_index: Option<usize> = None;
         for token in elements {
             let cur_index = non_dup_elements
@@ -176,11 +251,11 @@ where
 
         let mut cm_dist = vec![vec![0.0; elements_len]; elements_len];
         for (i, vector) in state_freq.iter().enumerate() {
-            let row_sum = vector.iter().fold(0, |acc, cur| acc + cur);
+            let row_sum = vector.iter().sum::<u64>();
             let mut cumulative_p = 0.0;
             for (j, count) in vector.iter().enumerate() {
                 if row_sum != 0 {
-                    cumulative_p = cumulative_p + (*count as f32 / row_sum as f32);
+                    cumulative_p += *count as f32 / row_sum as f32;
                     cm_dist[i][j] = cumulative_p;
                 }
             }
@@ -189,29 +264,14 @@ where
         MarkovCSM::new(non_dup_elements, cm_dist, elements_len)
     }
 
+    #[allow(clippy::should_implement_trait)]
     pub fn next(&mut self) -> &T {
         let mut rng = rand::thread_rng();
         self.next_rng(&mut rng)
     }
 
-    pub fn next_rng(&mut self, rng: &mut ThreadRng) -> &T {
-        let row_index = {
-            let mut i;
-            loop {
-                if self.pre_index != self.elements.len() {
-                    i = self.pre_index;
-                } else {
-                    i = rng.gen_range(0..self.elements.len());
-                }
-                let row_sum = self.cm_dist[i].iter().fold(0.0, |acc, cur| acc + cur);
-                if row_sum == 0.0 {
-                    self.initialize();
-                } else {
-                    break;
-                }
-            }
-            i
-        };
+    pub fn next_rng<R: Rng>(&mut self, rng: &mut R) -> &T {
+        let row_index = self.current_row(rng);
 
         let f = rng.gen::<f32>();
         let cur_index: usize = {
@@ -229,7 +289,115 @@ where
         &self.elements[cur_index]
     }
 
+    /// Returns a next possible state after reshaping the probabilities of
+    /// the row by `temperature`, as `MarkovChain::next_with_temperature`
+    /// does.
+    ///
+    /// The probability of each state is the difference between its
+    /// cumulative probability and the previous one. These are divided by
+    /// the largest of them, raised to the power of `1 / temperature`,
+    /// renormalized and accumulated again into a temporary CDF to sample
+    /// from. If no weight is left, the state is sampled as `next_rng` does.
+    pub fn next_with_temperature<R: Rng>(&mut self, rng: &mut R, temperature: f64) -> &T {
+        assert!(temperature > 0.0, "The temperature must be positive.");
+
+        let row_index = self.current_row(rng);
+
+        let mut prev_p = 0.0;
+        let probabilities = self.cm_dist[row_index]
+            .iter()
+            .map(|p| {
+                let diff = (*p as f64 - prev_p).max(0.0);
+                prev_p = *p as f64;
+                diff
+            })
+            .collect::<Vec<f64>>();
+        // Dividing by the maximum first keeps the most likely state from
+        // underflowing to 0 at low temperatures.
+        let max = probabilities.iter().cloned().fold(0.0, f64::max);
+        let weights = probabilities
+            .iter()
+            .map(|p| {
+                if *p == 0.0 {
+                    0.0
+                } else {
+                    (p / max).powf(1.0 / temperature)
+                }
+            })
+            .collect::<Vec<f64>>();
+        let sum = weights.iter().sum::<f64>();
+        if !(sum > 0.0 && sum.is_finite()) {
+            return self.next_rng(rng);
+        }
+        let mut cumulative_p = 0.0;
+        let cm_row = weights
+            .iter()
+            .map(|weight| {
+                cumulative_p += weight / sum;
+                cumulative_p
+            })
+            .collect::<Vec<f64>>();
+
+        let f = rng.gen::<f64>();
+        let cur_index = cm_row
+            .iter()
+            .position(|p| f <= *p)
+            .unwrap_or(cm_row.len() - 1);
+
+        self.pre_index = cur_index;
+        &self.elements[cur_index]
+    }
+
+    fn current_row<R: Rng>(&mut self, rng: &mut R) -> usize {
+        let mut i;
+        loop {
+            if self.pre_index != self.elements.len() {
+                i = self.pre_index;
+            } else {
+                i = rng.gen_range(0..self.elements.len());
+            }
+            let row_sum = self.cm_dist[i].iter().fold(0.0, |acc, cur| acc + cur);
+            if row_sum == 0.0 {
+                self.initialize();
+            } else {
+                break;
+            }
+        }
+        i
+    }
+
     pub fn initialize(&mut self) {
         self.pre_index = self.elements.len();
     }
 }
+
+/// The total count of each row when converting cumulative probabilities
+/// into transition counts.
+const PROBABILITY_SCALE: f32 = 1_000_000.0;
+
+impl<T> From<MarkovCSM<T>> for MarkovChain<T>
+where
+    T: Clone,
+    T: Eq,
+    T: Ord,
+    T: PartialOrd,
+    T: PartialEq,
+{
+    /// Rebuilds the alias tables from the probabilities of each row, which
+    /// are the differences of consecutive cumulative probabilities. The
+    /// current state of the chain is not carried over.
+    fn from(model: MarkovCSM<T>) -> MarkovChain<T> {
+        let mut transitions = Vec::new();
+        for (i, row) in model.cm_dist.iter().enumerate() {
+            let mut prev_p = 0.0;
+            for (j, p) in row.iter().enumerate() {
+                let count = ((p - prev_p) * PROBABILITY_SCALE).round() as u32;
+                if count > 0 {
+                    transitions.push((model.elements[i].clone(), model.elements[j].clone(), count));
+                }
+                prev_p = *p;
+            }
+        }
+        MarkovChain::from_transitions(&model.elements, &transitions).unwrap_or_default()
+    }
+}
@@ -4,8 +4,10 @@ fn main() {
     let text = [
         "I", "think", "that", "that", "that", "that", "that", "boy", "wrote", "is", "wrong",
     ];
-    let mut model = MarkovChain::from(&text);
+    let model = MarkovChain::from(&text);
+    let mut walker = model.spawn_walker();
+    let mut rng = rand::thread_rng();
     for _ in 0..20 {
-        print!("{} ", model.next());
+        print!("{} ", walker.next_rng(&mut rng));
     }
 }
@@ -0,0 +1,89 @@
+//! Weighted random sampling by Walker's Alias Method.
+//!
+//! The tables are built and sampled in the same way as the ones of the
+//! `weighted_rand` crate, which depends on the `std` features of `rand`
+//! and `serde`.
+
+use alloc::vec;
+use alloc::vec::Vec;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+/// A table which samples indexes in proportion to their weights in
+/// constant time.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone, Default)]
+pub(crate) struct WalkerTable {
+    /// The index which each column falls through to.
+    aliases: Vec<usize>,
+
+    /// The probability that each column falls through to its alias.
+    probs: Vec<f32>,
+}
+
+impl WalkerTable {
+    pub(crate) fn new(aliases: Vec<usize>, probs: Vec<f32>) -> WalkerTable {
+        WalkerTable { aliases, probs }
+    }
+
+    /// Builds a table which samples each index of `weights` in proportion
+    /// to its weight. If every weight is 0, the indexes are sampled
+    /// uniformly.
+    ///
+    /// The weights are multiplied by their number and added up as `u128`,
+    /// which cannot overflow, so any weights are exact.
+    pub(crate) fn from_weights(weights: &[u64]) -> WalkerTable {
+        let len = weights.len();
+        let weights = weights
+            .iter()
+            .map(|weight| u128::from(*weight) * len as u128)
+            .collect::<Vec<u128>>();
+        let sum = weights.iter().sum::<u128>();
+        if sum == 0 {
+            return WalkerTable::new(vec![0; len], vec![0.0; len]);
+        }
+
+        let mean = sum / len as u128;
+        let (mut below, mut above): (Vec<_>, Vec<_>) = weights
+            .into_iter()
+            .enumerate()
+            .partition(|(_, weight)| *weight <= mean);
+
+        let mut aliases = vec![0; len];
+        let mut probs = vec![0.0; len];
+        while let Some((index, weight)) = below.pop() {
+            match above.pop() {
+                Some((above_index, above_weight)) => {
+                    // The column of `index` is filled up with the weight of
+                    // `above_index`, which keeps the rest of its weight.
+                    let diff = mean - weight;
+                    aliases[index] = above_index;
+                    probs[index] = diff as f32 / mean as f32;
+                    let rest = above_weight - diff;
+                    if rest <= mean {
+                        below.push((above_index, rest));
+                    } else {
+                        above.push((above_index, rest));
+                    }
+                }
+                None => {
+                    aliases[index] = index;
+                    probs[index] = weight as f32 / mean as f32;
+                }
+            }
+        }
+        WalkerTable::new(aliases, probs)
+    }
+
+    /// Samples an index using `rng`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the table is empty.
+    pub(crate) fn next_rng<R: Rng>(&self, rng: &mut R) -> usize {
+        let index = rng.gen_range(0..self.probs.len());
+        if rng.gen::<f32>() < self.probs[index] {
+            return self.aliases[index];
+        }
+        index
+    }
+}